@@ -2,6 +2,7 @@
 
 #![cfg_attr(target_os = "wasi", feature(wasi_ext))]
 
+use std::collections::BTreeMap;
 use std::env::args_os;
 use std::fmt::Display;
 use std::fs::{self, File, ReadDir};
@@ -48,6 +49,32 @@ impl WorkingDir {
     }
 }
 
+/// Shell state persisted across the `try_fold` loop in [`main`], alongside the
+/// [`WorkingDir`]
+#[derive(Default)]
+struct ShellState {
+    vars: BTreeMap<String, String>,
+    aliases: BTreeMap<String, String>,
+}
+
+/// Recursively substitute the first word of `line` with its alias body, if any,
+/// guarding against alias cycles with a maximum expansion depth
+fn expand_aliases(line: &str, aliases: &BTreeMap<String, String>) -> anyhow::Result<String> {
+    let mut line = line.trim().to_string();
+    for _ in 0..=16 {
+        let (word, rest) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+        let Some(body) = aliases.get(word) else {
+            return Ok(line);
+        };
+        line = if rest.is_empty() {
+            body.clone()
+        } else {
+            format!("{body} {rest}")
+        };
+    }
+    bail!("alias expansion exceeded maximum depth of 16")
+}
+
 #[inline]
 fn strip_surround<const C: char>(s: &str) -> Option<&str> {
     s.strip_prefix(C).and_then(|s| s.strip_suffix(C))
@@ -61,17 +88,201 @@ fn unquote(s: &str) -> &str {
     s
 }
 
-const COMMANDS: [&str; 8] = ["accept", "cat", "cd", "help", "echo", "exit", "ls", "pwd"];
+/// Split `line` into whitespace-separated tokens, keeping quoted words intact and
+/// treating `|`, `>`, `>>` and `<` as standalone tokens even when not surrounded
+/// by whitespace (e.g. `cat a.txt>b.txt`).
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut cur = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                cur.push(c);
+                for nc in chars.by_ref() {
+                    cur.push(nc);
+                    if nc == c {
+                        break;
+                    }
+                }
+            }
+            '|' => {
+                if !cur.is_empty() {
+                    tokens.push(std::mem::take(&mut cur));
+                }
+                tokens.push("|".into());
+            }
+            '>' => {
+                if !cur.is_empty() {
+                    tokens.push(std::mem::take(&mut cur));
+                }
+                if chars.peek() == Some(&'>') {
+                    chars.next();
+                    tokens.push(">>".into());
+                } else {
+                    tokens.push(">".into());
+                }
+            }
+            '<' => {
+                if !cur.is_empty() {
+                    tokens.push(std::mem::take(&mut cur));
+                }
+                tokens.push("<".into());
+            }
+            c if c.is_whitespace() => {
+                if !cur.is_empty() {
+                    tokens.push(std::mem::take(&mut cur));
+                }
+            }
+            c => cur.push(c),
+        }
+    }
+    if !cur.is_empty() {
+        tokens.push(cur);
+    }
+    tokens
+}
+
+/// Substitute every `$NAME` and `${NAME}` occurring in `token` with the corresponding
+/// value from `vars`, treating unknown names as empty
+fn expand_vars(token: &str, vars: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut chars = token.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            let mut name = String::new();
+            for nc in chars.by_ref() {
+                if nc == '}' {
+                    break;
+                }
+                name.push(nc);
+            }
+            out.push_str(vars.get(&name).map(String::as_str).unwrap_or(""));
+            continue;
+        }
+        let mut name = String::new();
+        if chars.peek() == Some(&'?') {
+            name.push('?');
+            chars.next();
+        } else {
+            while let Some(&nc) = chars.peek() {
+                if nc.is_alphanumeric() || nc == '_' {
+                    name.push(nc);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+        }
+        if name.is_empty() {
+            out.push('$');
+        } else {
+            out.push_str(vars.get(&name).map(String::as_str).unwrap_or(""));
+        }
+    }
+    out
+}
+
+/// A redirection of a [`Command`]'s output to a file
+struct Redirect {
+    path: Utf8PathBuf,
+    append: bool,
+}
+
+/// A single stage of a [`Pipeline`]
+#[derive(Default)]
+struct Command {
+    argv: Vec<String>,
+    stdin: Option<Utf8PathBuf>,
+    stdout: Option<Redirect>,
+}
+
+/// One or more [`Command`]s chained together with `|`, each optionally reading
+/// its input from or writing its output to a file
+#[derive(Default)]
+struct Pipeline {
+    commands: Vec<Command>,
+}
 
-const ACCEPT_USAGE: &str = "Usage: accept FILE";
+/// Parse `line` into a [`Pipeline`], splitting on `|`, extracting `>`, `>>` and `<`
+/// redirections from each resulting segment and expanding `$NAME`/`${NAME}` variable
+/// references against `vars`
+fn parse_pipeline(line: &str, vars: &BTreeMap<String, String>) -> anyhow::Result<Pipeline> {
+    let tokens = tokenize(line);
+    let mut commands = Vec::new();
+    for segment in tokens.split(|token| token == "|") {
+        let mut command = Command::default();
+        let mut tokens = segment.iter();
+        while let Some(token) = tokens.next() {
+            match token.as_str() {
+                ">" => {
+                    let path = tokens.next().context("missing redirect target after `>`")?;
+                    command.stdout = Some(Redirect {
+                        path: expand_vars(unquote(path), vars).into(),
+                        append: false,
+                    });
+                }
+                ">>" => {
+                    let path = tokens
+                        .next()
+                        .context("missing redirect target after `>>`")?;
+                    command.stdout = Some(Redirect {
+                        path: expand_vars(unquote(path), vars).into(),
+                        append: true,
+                    });
+                }
+                "<" => {
+                    let path = tokens.next().context("missing redirect source after `<`")?;
+                    command.stdin = Some(expand_vars(unquote(path), vars).into());
+                }
+                token => command.argv.push(expand_vars(unquote(token), vars)),
+            }
+        }
+        if command.argv.is_empty() && command.stdin.is_none() && command.stdout.is_none() {
+            continue;
+        }
+        commands.push(command);
+    }
+    Ok(Pipeline { commands })
+}
+
+const COMMANDS: [&str; 12] = [
+    "accept", "alias", "cat", "cd", "help", "echo", "env", "exit", "ls", "pwd", "set", "unalias",
+];
+
+const ACCEPT_USAGE: &str = "Usage: accept FILE [CMD...]";
+
+const ALIAS_USAGE: &str = "Usage: alias NAME=COMMAND";
+
+fn alias(state: &mut ShellState, arg: &str) -> anyhow::Result<()> {
+    let (name, command) = arg.split_once('=').context(ALIAS_USAGE)?;
+    state.aliases.insert(name.into(), unquote(command).into());
+    Ok(())
+}
 
 #[inline]
 fn into_listener(fd: impl Into<OwnedFd>) -> TcpListener {
     fd.into().into()
 }
 
-fn accept(dir: &WorkingDir, path: impl AsRef<str>) -> anyhow::Result<Vec<u8>> {
-    let path = dir.join(unquote(path.as_ref()));
+/// Accept a single connection on the preopened socket at `path`, run the bytes
+/// received from it as shell input and write the produced output back to the
+/// stream before closing it. If `cmd` is non-empty, the received bytes are piped
+/// to `cmd` instead of being interpreted as one or more shell lines. A line that
+/// fails to parse/execute reports `Error: ...` into the response and updates `$?`,
+/// the same way the top-level REPL does, rather than aborting the whole request.
+fn accept(
+    dir: &WorkingDir,
+    state: &mut ShellState,
+    path: &str,
+    cmd: &[String],
+) -> anyhow::Result<Vec<u8>> {
+    let path = dir.join(path);
     let (mut stream, _) = File::options()
         .read(true)
         .write(true)
@@ -81,22 +292,62 @@ fn accept(dir: &WorkingDir, path: impl AsRef<str>) -> anyhow::Result<Vec<u8>> {
         .accept()
         .with_context(|| format!("failed to accept connection on `{path}`"))?;
 
-    let mut buf = Default::default();
+    let mut request = Default::default();
     stream
-        .read_to_end(&mut buf)
+        .read_to_end(&mut request)
         .context("failed to read from stream")?;
-    Ok(buf)
+
+    let response = if cmd.is_empty() {
+        let request = std::str::from_utf8(&request).context("request is not valid UTF-8")?;
+        let mut dir = WorkingDir {
+            path: dir.path.clone(),
+        };
+        let mut response = Vec::new();
+        for line in request.lines() {
+            match handle(&dir, state, line) {
+                Ok(effect) => {
+                    if let Some(new_dir) = effect.dir {
+                        dir = new_dir;
+                    }
+                    if let Some(out) = effect.out {
+                        response.extend_from_slice(&out);
+                        response.push(b'\n');
+                    }
+                    state.vars.insert("?".into(), "0".into());
+                }
+                Err(e) => {
+                    response.extend_from_slice(format!("Error: {e:?}\n").as_bytes());
+                    state.vars.insert("?".into(), "1".into());
+                }
+            }
+        }
+        response
+    } else {
+        run_command(dir, state, cmd, &request)?
+            .out
+            .unwrap_or_default()
+    };
+
+    stream
+        .write_all(&response)
+        .context("failed to write response to stream")?;
+    Ok(request)
 }
 
-const CAT_USAGE: &str = "Usage: cat FILE";
+const CAT_USAGE: &str = "Usage: cat [FILE]";
 
-fn cat(dir: &WorkingDir, path: impl AsRef<str>) -> anyhow::Result<Vec<u8>> {
-    let path = dir.join(unquote(path.as_ref()));
-    fs::read(&path).with_context(|| format!("failed to read `{path}`"))
+fn cat(dir: &WorkingDir, path: Option<&str>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    match path {
+        Some(path) => {
+            let path = dir.join(path);
+            fs::read(&path).with_context(|| format!("failed to read `{path}`"))
+        }
+        None => Ok(input.to_vec()),
+    }
 }
 
-fn cd(dir: &WorkingDir, path: impl AsRef<str>) -> anyhow::Result<WorkingDir> {
-    let path = Utf8Path::new(unquote(path.as_ref()));
+fn cd(dir: &WorkingDir, path: &str) -> anyhow::Result<WorkingDir> {
+    let path = Utf8Path::new(path);
     if path.is_absolute() {
         WorkingDir::open(path)
     } else {
@@ -104,13 +355,20 @@ fn cd(dir: &WorkingDir, path: impl AsRef<str>) -> anyhow::Result<WorkingDir> {
     }
 }
 
-const ECHO_USAGE: &str = "Usage: echo [WORD|\"TEXT\"|'TEXT'] > FILE";
+fn echo(args: &[String]) -> Vec<u8> {
+    args.join(" ").into_bytes()
+}
+
+const ENV_USAGE: &str = "Usage: env";
 
-fn echo(dir: &WorkingDir, args: impl AsRef<str>) -> anyhow::Result<()> {
-    let (text, path) = args.as_ref().rsplit_once('>').context("missing `>`")?;
-    let text = unquote(text);
-    let path = dir.join(unquote(path));
-    fs::write(&path, text).with_context(|| format!("failed to write `{text}` to `{path}`"))
+fn env(state: &ShellState) -> Vec<u8> {
+    state
+        .vars
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .into()
 }
 
 const EXIT_USAGE: &str = "Usage: exit";
@@ -128,29 +386,150 @@ fn help() -> Vec<u8> {
     format!(r#"Available commands: {}"#, COMMANDS.join(r#", "#)).into()
 }
 
-fn ls(dir: &WorkingDir, path: Option<&str>) -> anyhow::Result<Vec<u8>> {
-    #[inline]
-    fn format_dir(dir: ReadDir) -> anyhow::Result<Vec<u8>> {
-        dir.map(|entry| {
-            entry
-                .context("failed to read directory entry")?
-                .file_name()
-                .into_string()
-                .map_err(|name| anyhow!("failed to parse entry name `{}`", name.to_string_lossy()))
-        })
-        .collect::<anyhow::Result<Vec<_>>>()
-        .map(|names| names.join(" ").into())
+const LS_USAGE: &str = "Usage: ls [-R] [PATH|PATTERN]";
+
+#[inline]
+fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+/// Match the byte `c` against the contents of a character class `[...]`/`[a-z]`,
+/// honouring a leading `!`/`^` negation
+fn match_class(class: &[u8], c: u8) -> bool {
+    let (negate, class) = match class.first() {
+        Some(b'!' | b'^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == b'-' {
+            if class[i] <= c && c <= class[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if class[i] == c {
+                matched = true;
+            }
+            i += 1;
+        }
     }
+    matched != negate
+}
+
+/// Match `name` against a shell glob `pattern` containing `*`, `?` and
+/// `[...]`/`[a-z]` character classes, anchored at both ends, backtracking over
+/// progressively longer spans for `*`
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => (0..=name.len()).any(|i| glob_match(&pattern[1..], &name[i..])),
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(b'['), Some(&c)) => match pattern.iter().position(|&b| b == b']') {
+            Some(end) if end > 1 && match_class(&pattern[1..end], c) => {
+                glob_match(&pattern[end + 1..], &name[1..])
+            }
+            _ => false,
+        },
+        (Some(&p), Some(&c)) if p == c => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
 
-    if let Some(path) = path {
-        let path = dir.join(unquote(path));
-        fs::read_dir(&path)
-            .with_context(|| format!("failed to list directory `{path}`"))
-            .and_then(format_dir)
+fn matches_glob(pattern: &str, name: &str) -> bool {
+    glob_match(pattern.as_bytes(), name.as_bytes())
+}
+
+#[inline]
+fn format_dir(dir: ReadDir, pattern: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    dir.map(|entry| {
+        entry
+            .context("failed to read directory entry")?
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow!("failed to parse entry name `{}`", name.to_string_lossy()))
+    })
+    .collect::<anyhow::Result<Vec<_>>>()
+    .map(|names| {
+        names
+            .into_iter()
+            .filter(|name| {
+                pattern
+                    .map(|pattern| matches_glob(pattern, name))
+                    .unwrap_or(true)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+            .into()
+    })
+}
+
+/// Depth-first walk of `path` (relative to `base`), appending one `path:`-prefixed
+/// group of matching entries per directory to `out`, like `ls -R`
+fn ls_recursive(
+    base: &Utf8Path,
+    path: &Utf8Path,
+    pattern: Option<&str>,
+    out: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+    let rel = path.strip_prefix(base).unwrap_or(path);
+    let rel = if rel.as_str().is_empty() {
+        "."
     } else {
-        fs::read_dir(dir.deref())
-            .with_context(|| format!("failed to list working directory contents in `{dir}`"))
-            .and_then(format_dir)
+        rel.as_str()
+    };
+
+    let mut names = Vec::new();
+    let mut subdirs = Vec::new();
+    for entry in fs::read_dir(path).with_context(|| format!("failed to list directory `{path}`"))? {
+        let entry = entry.context("failed to read directory entry")?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow!("failed to parse entry name `{}`", name.to_string_lossy()))?;
+        if entry
+            .file_type()
+            .with_context(|| format!("failed to read file type of `{path}/{name}`"))?
+            .is_dir()
+        {
+            subdirs.push(path.join(&name));
+        }
+        if pattern
+            .map(|pattern| matches_glob(pattern, &name))
+            .unwrap_or(true)
+        {
+            names.push(name);
+        }
+    }
+
+    if !out.is_empty() {
+        out.push(b'\n');
+    }
+    out.extend_from_slice(format!("{rel}:\n").as_bytes());
+    out.extend_from_slice(names.join(" ").as_bytes());
+    out.push(b'\n');
+    for subdir in subdirs {
+        ls_recursive(base, &subdir, pattern, out)?;
+    }
+    Ok(())
+}
+
+fn ls(dir: &WorkingDir, arg: Option<&str>, recursive: bool) -> anyhow::Result<Vec<u8>> {
+    let (base, pattern) = match arg {
+        Some(arg) if is_glob(arg) => (dir.deref().clone(), Some(arg)),
+        Some(arg) => (dir.join(arg), None),
+        None => (dir.deref().clone(), None),
+    };
+
+    if recursive {
+        let mut out = Vec::new();
+        ls_recursive(&base, &base, pattern, &mut out)?;
+        Ok(out)
+    } else {
+        fs::read_dir(&base)
+            .with_context(|| format!("failed to list directory `{base}`"))
+            .and_then(|entries| format_dir(entries, pattern))
     }
 }
 
@@ -160,6 +539,24 @@ fn pwd(dir: &WorkingDir) -> Vec<u8> {
     format!("{dir}").into()
 }
 
+const SET_USAGE: &str = "Usage: set NAME=VALUE";
+
+fn set(state: &mut ShellState, arg: &str) -> anyhow::Result<()> {
+    let (name, value) = arg.split_once('=').context(SET_USAGE)?;
+    state.vars.insert(name.into(), unquote(value).into());
+    Ok(())
+}
+
+const UNALIAS_USAGE: &str = "Usage: unalias NAME";
+
+fn unalias(state: &mut ShellState, name: &str) -> anyhow::Result<()> {
+    state
+        .aliases
+        .remove(name)
+        .map(|_| ())
+        .ok_or_else(|| anyhow!("no alias named `{name}`"))
+}
+
 /// Effect of execution of a command
 #[derive(Default)]
 struct Effect {
@@ -209,7 +606,69 @@ impl From<Vec<u8>> for Effect {
     }
 }
 
-fn handle(dir: &WorkingDir, line: impl AsRef<str>) -> anyhow::Result<Effect> {
+/// Run a single already-parsed command `argv` against `input`, the bytes fed to its
+/// standard input: the contents of its `<` redirect if it has one, otherwise the
+/// output of the previous stage of its pipeline (or empty, if it is the first stage)
+fn run_command(
+    dir: &WorkingDir,
+    state: &mut ShellState,
+    argv: &[String],
+    input: &[u8],
+) -> anyhow::Result<Effect> {
+    match argv {
+        [] => bail!("failed to parse line"),
+        [cmd, rest @ ..] => match (cmd.as_str(), rest) {
+            ("accept", []) => bail!(ACCEPT_USAGE),
+            ("accept", [path]) => accept(dir, state, path, &[]).map(Into::into),
+            ("accept", [path, cmd @ ..]) => accept(dir, state, path, cmd).map(Into::into),
+
+            ("alias", [arg]) => alias(state, arg).map(Into::into),
+            ("alias", _) => bail!(ALIAS_USAGE),
+
+            ("cat", []) => cat(dir, None, input).map(Into::into),
+            ("cat", [path]) => cat(dir, Some(path), input).map(Into::into),
+            ("cat", _) => bail!(CAT_USAGE),
+
+            ("cd", []) => Ok(Default::default()),
+            ("cd", [path]) => cd(dir, path).map(Into::into),
+            ("cd", _) => bail!("Usage: cd [PATH]"),
+
+            ("echo", args) => Ok(echo(args).into()),
+
+            ("env", []) => Ok(env(state).into()),
+            ("env", _) => bail!(ENV_USAGE),
+
+            ("exit", []) => Ok(exit()),
+            ("exit", _) => bail!(EXIT_USAGE),
+
+            ("help", []) => Ok(help().into()),
+            ("help", _) => bail!(HELP_USAGE),
+
+            ("ls", []) => ls(dir, None, false).map(Into::into),
+            ("ls", [flag]) if flag == "-R" => ls(dir, None, true).map(Into::into),
+            ("ls", [arg]) => ls(dir, Some(arg), false).map(Into::into),
+            ("ls", [flag, arg]) if flag == "-R" => ls(dir, Some(arg), true).map(Into::into),
+            ("ls", _) => bail!(LS_USAGE),
+
+            ("pwd", []) => Ok(pwd(dir).into()),
+            ("pwd", _) => bail!(PWD_USAGE),
+
+            ("set", [arg]) => set(state, arg).map(Into::into),
+            ("set", _) => bail!(SET_USAGE),
+
+            ("unalias", [name]) => unalias(state, name).map(Into::into),
+            ("unalias", _) => bail!(UNALIAS_USAGE),
+
+            _ => bail!("failed to parse line"),
+        },
+    }
+}
+
+fn handle(
+    dir: &WorkingDir,
+    state: &mut ShellState,
+    line: impl AsRef<str>,
+) -> anyhow::Result<Effect> {
     let line = line.as_ref().trim();
     if line.is_empty() {
         return Ok(Default::default());
@@ -221,33 +680,46 @@ fn handle(dir: &WorkingDir, line: impl AsRef<str>) -> anyhow::Result<Effect> {
     {
         bail!("line must start with an alphanumeric character or whitespace")
     }
-    match line.split_once(' ') {
-        None if line == "accept" => bail!(ACCEPT_USAGE),
-        Some(("accept", path)) => accept(dir, path).map(Into::into),
-
-        None if line == "cat" => bail!(CAT_USAGE),
-        Some(("cat", args)) => cat(dir, args).map(Into::into),
-
-        None if line == "cd" => Ok(Default::default()),
-        Some(("cd", args)) => cd(dir, args).map(Into::into),
-
-        None if line == "echo" => bail!(ECHO_USAGE),
-        Some(("echo", args)) => echo(dir, args).map(Into::into),
-
-        None if line == "exit" => Ok(exit()),
-        Some(("exit", _)) => bail!(EXIT_USAGE),
-
-        None if line == "help" => Ok(help().into()),
-        Some(("help", _)) => bail!(HELP_USAGE),
-
-        None if line == "ls" => ls(dir, None).map(Into::into),
-        Some(("ls", path)) => ls(dir, Some(path)).map(Into::into),
-
-        None if line == "pwd" => Ok(pwd(dir).into()),
-        Some(("pwd", _)) => bail!(PWD_USAGE),
-
-        _ => bail!("failed to parse line"),
+    let line = expand_aliases(line, &state.aliases)?;
+    let Pipeline { commands } = parse_pipeline(&line, &state.vars)?;
+    if commands.is_empty() {
+        bail!("failed to parse line")
     }
+    let n = commands.len();
+    let mut input = Vec::new();
+    let mut effect = Effect::default();
+    for (i, command) in commands.into_iter().enumerate() {
+        let stage_input = if let Some(path) = &command.stdin {
+            let path = dir.join(path);
+            fs::read(&path).with_context(|| format!("failed to read `{path}`"))?
+        } else {
+            std::mem::take(&mut input)
+        };
+        let stage = run_command(dir, state, &command.argv, &stage_input)?;
+        let is_last = i + 1 == n;
+        if is_last {
+            effect.dir = stage.dir;
+            effect.exit = stage.exit;
+        }
+        if let Some(out) = stage.out {
+            if let Some(Redirect { path, append }) = &command.stdout {
+                let path = dir.join(path);
+                File::options()
+                    .create(true)
+                    .write(true)
+                    .append(*append)
+                    .truncate(!*append)
+                    .open(&path)
+                    .and_then(|mut file| file.write_all(&out))
+                    .with_context(|| format!("failed to write to `{path}`"))?;
+            } else if is_last {
+                effect.out = Some(out);
+            } else {
+                input = out;
+            }
+        }
+    }
+    Ok(effect)
 }
 
 fn prompt(dir: &WorkingDir) -> String {
@@ -260,12 +732,14 @@ fn main() -> anyhow::Result<()> {
     }
     let mut stdout = stdout();
     let dir = WorkingDir::open("/")?;
+    let mut state = ShellState::default();
+    state.vars.insert("?".into(), "0".into());
     eprint!("{}", prompt(&dir));
     stdin()
         .lines()
         .try_fold(dir, |dir, line| {
             let line = line.context("failed to read line from STDIN")?;
-            let dir = match handle(&dir, line).and_then(|Effect { dir, out, exit }| {
+            let result = handle(&dir, &mut state, line).and_then(|Effect { dir, out, exit }| {
                 if let Some(out) = out {
                     copy(&mut out.as_slice(), &mut stdout)
                         .context("failed to write output to STDOUT")?;
@@ -277,11 +751,19 @@ fn main() -> anyhow::Result<()> {
                     process::exit(code)
                 }
                 Ok(dir)
-            }) {
-                Ok(None) => dir,
-                Ok(Some(dir)) => dir,
+            });
+            let dir = match result {
+                Ok(None) => {
+                    state.vars.insert("?".into(), "0".into());
+                    dir
+                }
+                Ok(Some(dir)) => {
+                    state.vars.insert("?".into(), "0".into());
+                    dir
+                }
                 Err(e) => {
                     eprintln!("Error: {:?}", e);
+                    state.vars.insert("?".into(), "1".into());
                     dir
                 }
             };